@@ -3,9 +3,10 @@
 use gl;
 
 use hal::{self, buffer, command, image, memory, pass, pso, query, ColorSlot};
-use hal::format::ChannelType;
+use hal::format::{self, ChannelType};
 
 use {native as n, Backend};
+use info::PrivateCaps;
 use pool::{self, BufferMemory};
 
 use std::borrow::Borrow;
@@ -78,6 +79,20 @@ pub enum Command {
     },
     SetScissors(u32, BufferSlice),
     SetBlendColor(pso::ColorValue),
+    /// Clear a region of a texture level without a framebuffer (`glClearTexSubImage`).
+    /// Arguments: texture, level, offset, size, format, type and the raw clear value.
+    ClearTexture(
+        n::Texture,
+        gl::types::GLint,
+        [gl::types::GLint; 3],
+        [gl::types::GLsizei; 3],
+        gl::types::GLenum,
+        gl::types::GLenum,
+        [u32; 4],
+    ),
+    SetAlphaCoverage(bool),
+    SetDepth(pso::DepthTest),
+    SetStencil(pso::StencilTest, (pso::StencilValue, pso::StencilValue)),
 
     /// Clear floating-point color drawbuffer of bound framebuffer.
     ClearBufferColorF(DrawBuffer, [f32; 4]),
@@ -98,7 +113,7 @@ pub enum Command {
     SetPatchSize(gl::types::GLint),
     BindProgram(gl::types::GLuint),
     BindBlendSlot(ColorSlot, pso::ColorBlendDesc),
-    BindAttribute(n::AttributeDesc, gl::types::GLuint, gl::types::GLsizei, n::VertexAttribFunction),
+    BindAttribute(n::AttributeDesc, gl::types::GLuint, gl::types::GLsizei, n::VertexAttribFunction, pso::InstanceRate),
     //UnbindAttribute(n::AttributeDesc),
     CopyBufferToBuffer(n::RawBuffer, n::RawBuffer, command::BufferCopy),
     CopyBufferToTexture(n::RawBuffer, n::Texture, command::BufferImageCopy),
@@ -132,6 +147,8 @@ struct Cache {
     primitive: Option<gl::types::GLenum>,
     // Active index type, set by the current index buffer.
     index_type: Option<hal::IndexType>,
+    // Byte offset into the current index buffer.
+    index_buffer_offset: buffer::Offset,
     // Stencil reference values (front, back).
     stencil_ref: Option<(pso::StencilValue, pso::StencilValue)>,
     // Blend color.
@@ -147,6 +164,12 @@ struct Cache {
     program: Option<gl::types::GLuint>,
     // Blend per attachment.
     blend_targets: Option<Vec<Option<pso::ColorBlendDesc>>>,
+    // Alpha-to-coverage multisampling.
+    alpha_coverage: Option<bool>,
+    // Depth test, set by the current pipeline.
+    depth_test: Option<pso::DepthTest>,
+    // Stencil test, set by the current pipeline.
+    stencil_test: Option<pso::StencilTest>,
     // Maps bound vertex buffer offset (index) to handle.
     vertex_buffers: Vec<gl::types::GLuint>,
     // Active vertex buffer descriptions.
@@ -160,6 +183,7 @@ impl Cache {
         Cache {
             primitive: None,
             index_type: None,
+            index_buffer_offset: 0,
             stencil_ref: None,
             blend_color: None,
             framebuffer: None,
@@ -167,6 +191,9 @@ impl Cache {
             patch_size: None,
             program: None,
             blend_targets: None,
+            alpha_coverage: None,
+            depth_test: None,
+            stencil_test: None,
             vertex_buffers: Vec::new(),
             vertex_buffer_descs: Vec::new(),
             attributes: Vec::new(),
@@ -174,17 +201,21 @@ impl Cache {
     }
 }
 
-// This is a subset of the device limits stripped down to the ones needed
-// for command buffer validation.
+// This is a subset of the device limits and private capabilities stripped
+// down to the ones needed for command buffer validation and recording.
 #[derive(Debug, Clone, Copy)]
 pub struct Limits {
     max_viewports: usize,
+    clear_texture: bool,
+    independent_blending: bool,
 }
 
-impl From<hal::Limits> for Limits {
-    fn from(l: hal::Limits) -> Self {
+impl Limits {
+    pub(crate) fn new(limits: &hal::Limits, features: hal::Features, private_caps: &PrivateCaps) -> Self {
         Limits {
-            max_viewports: l.max_viewports,
+            max_viewports: limits.max_viewports,
+            clear_texture: private_caps.clear_texture,
+            independent_blending: features.contains(hal::Features::INDEPENDENT_BLENDING),
         }
     }
 }
@@ -305,7 +336,15 @@ impl RawCommandBuffer {
     }
 
     fn update_blend_targets(&mut self, blend_targets: &Vec<pso::ColorBlendDesc>) {
-        let max_blend_slots = blend_targets.len();
+        // Without separate blending slots, the first target applies to all attachments.
+        let max_blend_slots = if self.limits.independent_blending {
+            blend_targets.len()
+        } else {
+            if blend_targets.iter().skip(1).any(|target| *target != blend_targets[0]) {
+                error!("Separate blending slots are not supported");
+            }
+            blend_targets.len().min(1)
+        };
 
         if max_blend_slots > 0 {
             match self.cache.blend_targets {
@@ -320,7 +359,7 @@ impl RawCommandBuffer {
             };
         }
 
-        for (slot, blend_target) in blend_targets.iter().enumerate() {
+        for (slot, blend_target) in blend_targets.iter().take(max_blend_slots).enumerate() {
             let mut update_blend = false;
             if let Some(ref mut cached_targets) = self.cache.blend_targets {
                 if let Some(cached_target) = cached_targets.get(slot) {
@@ -347,6 +386,21 @@ impl RawCommandBuffer {
         }
     }
 
+    // Set the stencil state once both the pipeline stencil test
+    // and the reference values (if required) are known.
+    fn update_stencil(&mut self) {
+        let (stencil, refs) = match self.cache.stencil_test {
+            Some(stencil @ pso::StencilTest::On { .. }) => match self.cache.stencil_ref {
+                Some(refs) => (stencil, refs),
+                None => return,
+            },
+            Some(stencil @ pso::StencilTest::Off) => (stencil, (0, 0)),
+            None => return,
+        };
+
+        self.push_cmd(Command::SetStencil(stencil, refs));
+    }
+
     pub(crate) fn bind_attributes(&mut self) {
         let Cache {
             ref attributes,
@@ -370,13 +424,11 @@ impl RawCommandBuffer {
 
             let desc = &vertex_buffer_descs[binding];
 
-            assert_eq!(desc.rate, 0); // TODO: Input rate
-
             push_cmd_internal(
                 &self.id,
                 &mut self.memory,
                 &mut self.buf,
-                Command::BindAttribute(*attribute, handle, desc.stride as _, attribute.vertex_attrib_fn)
+                Command::BindAttribute(*attribute, handle, desc.stride as _, attribute.vertex_attrib_fn, desc.rate)
             );
         }
     }
@@ -466,9 +518,20 @@ impl RawCommandBuffer {
         let draw_buffers = self.add(&draw_buffers);
         self.push_cmd(Command::DrawBuffers(draw_buffers));
 
+        let mut unlocked_depth_stencil = false;
         for cmd in clear_cmds {
+            if let Command::ClearBufferDepthStencil(..) = cmd {
+                unlocked_depth_stencil = true;
+            }
             self.push_cmd(cmd);
         }
+
+        // Clearing resets the depth and stencil write masks,
+        // force the next pipeline to set its state again.
+        if unlocked_depth_stencil {
+            self.cache.depth_test = None;
+            self.cache.stencil_test = None;
+        }
     }
 }
 
@@ -609,14 +672,48 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         &mut self,
         image: &n::Image,
         _: image::Layout,
-        _range: image::SubresourceRange,
+        range: image::SubresourceRange,
         value: command::ClearColorRaw,
     ) {
-        // TODO: clearing strategies
-        //  1.  < GL 3.0 / GL ES 3.0: glClear
+        // Clearing strategies:
+        //  1.  < GL 3.0 / GL ES 3.0: glClear (TODO)
         //  2.  < GL 4.4: glClearBuffer
         //  3. >= GL 4.4: glClearTexSubImage
 
+        // 3. ClearTexSubImage, requires the dimensions to clear the layer range of each level.
+        // The data is stored as-is, so sRGB images are left to the framebuffer path,
+        // which encodes the linear clear value.
+        match (image.kind, image.dimensions) {
+            (n::ImageKind::Texture(id), Some(dimensions))
+                if self.limits.clear_texture &&
+                    range.aspects == format::Aspects::COLOR &&
+                    image.channel != ChannelType::Srgb =>
+            {
+                let (format, ty) = match image.channel {
+                    ChannelType::Uint => (gl::RGBA_INTEGER, gl::UNSIGNED_INT),
+                    ChannelType::Int => (gl::RGBA_INTEGER, gl::INT),
+                    _ => (gl::RGBA, gl::FLOAT),
+                };
+                let first_layer = range.layers.start as gl::types::GLint;
+                let num_layers = (range.layers.end - range.layers.start) as gl::types::GLsizei;
+                for level in range.levels {
+                    let extent = dimensions.level_extent(level);
+                    let (width, height, depth) = (extent.width as _, extent.height as _, extent.depth as _);
+                    // Array layers are the last dimension of the GL texture.
+                    let (offset, size) = match dimensions {
+                        image::Kind::D1(..) => ([0, first_layer, 0], [width, num_layers, 1]),
+                        image::Kind::D2(..) => ([0, 0, first_layer], [width, height, num_layers]),
+                        image::Kind::D3(..) => ([0, 0, 0], [width, height, depth]),
+                    };
+                    self.push_cmd(Command::ClearTexture(
+                        id, level as _, offset, size, format, ty, unsafe { value.uint32 },
+                    ));
+                }
+                return;
+            }
+            _ => {}
+        }
+
         // 2. ClearBuffer
         // TODO: reset color mask
         let fbo = self.fbo;
@@ -687,12 +784,16 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
     }
 
     fn bind_index_buffer(&mut self, ibv: buffer::IndexBufferView<Backend>) {
-        // TODO: how can we incorporate the buffer offset?
-        if ibv.offset > 0 {
-            warn!("Non-zero index buffer offset currently not handled.");
+        if !ibv.buffer.usage.contains(buffer::Usage::INDEX) {
+            error!("Buffer {} bound as index buffer was not created with index usage", ibv.buffer.raw);
+            self.cache.error_state = true;
+            // Forget the previous binding, indexed draws would silently use it otherwise.
+            self.cache.index_type = None;
+            return;
         }
 
         self.cache.index_type = Some(ibv.index_type);
+        self.cache.index_buffer_offset = ibv.offset;
         self.push_cmd(Command::BindIndexBuffer(ibv.buffer.raw));
     }
 
@@ -781,7 +882,10 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         // Only cache the stencil references values until
         // we assembled all the pieces to set the stencil state
         // from the pipeline.
-        self.cache.stencil_ref = Some((front, back));
+        if self.cache.stencil_ref != Some((front, back)) {
+            self.cache.stencil_ref = Some((front, back));
+            self.update_stencil();
+        }
     }
 
     fn set_blend_constants(&mut self, cv: pso::ColorValue) {
@@ -797,6 +901,8 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
             patch_size,
             program,
             ref blend_targets,
+            alpha_coverage,
+            depth_stencil,
             ref attributes,
             ref vertex_buffers,
         } = *pipeline;
@@ -822,6 +928,26 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         self.cache.vertex_buffer_descs = vertex_buffers.clone();
 
         self.update_blend_targets(blend_targets);
+
+        if self.cache.alpha_coverage != Some(alpha_coverage) {
+            self.cache.alpha_coverage = Some(alpha_coverage);
+            self.push_cmd(Command::SetAlphaCoverage(alpha_coverage));
+        }
+
+        let (depth_test, stencil_test) = match depth_stencil {
+            Some(ds) => (ds.depth, ds.stencil),
+            None => (pso::DepthTest::Off, pso::StencilTest::Off),
+        };
+
+        if self.cache.depth_test != Some(depth_test) {
+            self.cache.depth_test = Some(depth_test);
+            self.push_cmd(Command::SetDepth(depth_test));
+        }
+
+        if self.cache.stencil_test != Some(stencil_test) {
+            self.cache.stencil_test = Some(stencil_test);
+            self.update_stencil();
+        }
     }
 
     fn bind_graphics_descriptor_sets<T>(
@@ -999,7 +1125,7 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
                         primitive,
                         index_type,
                         index_count: indices.end - indices.start,
-                        index_buffer_offset: start as _,
+                        index_buffer_offset: self.cache.index_buffer_offset + start as buffer::Offset,
                         base_vertex,
                         instances,
                     }
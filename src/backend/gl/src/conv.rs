@@ -75,7 +75,6 @@ pub fn format_to_gl_format(format: Format) -> Option<(gl::types::GLint, gl::type
     use hal::format::Format::*;
     use gl::*;
     use native::VertexAttribFunction::*;
-    let _ = Double; //mark as used
     // TODO: Add more formats and error handling for `None`
     let format = match format {
         R8Uint => (1, UNSIGNED_BYTE, Integer),
@@ -105,6 +104,10 @@ pub fn format_to_gl_format(format: Format) -> Option<(gl::types::GLint, gl::type
         Rgba32Uint => (4, UNSIGNED_INT, Integer),
         Rgba32Int => (4, INT, Integer),
         Rgba32Float => (4, FLOAT, Float),
+        R64Float => (1, DOUBLE, Double),
+        Rg64Float => (2, DOUBLE, Double),
+        Rgb64Float => (3, DOUBLE, Double),
+        Rgba64Float => (4, DOUBLE, Double),
 
         _ => return None,
     };
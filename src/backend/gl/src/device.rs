@@ -18,7 +18,7 @@ use hal::range::RangeArg;
 use spirv_cross::{glsl, spirv, ErrorCode as SpirvErrorCode};
 
 use {Backend as B, Share, Surface, Swapchain, Starc};
-use {conv, native as n, state};
+use {command as com, conv, native as n, state};
 use info::LegacyFeatures;
 use pool::{BufferMemory, OwnedBuffer, RawCommandPool};
 
@@ -76,6 +76,22 @@ pub fn get_program_log(gl: &gl::Gl, name: n::Program) -> String {
     }
 }
 
+/// Human-readable reason for an incomplete framebuffer status.
+fn framebuffer_status_reason(status: GLenum) -> &'static str {
+    match status {
+        gl::FRAMEBUFFER_UNDEFINED => "default framebuffer doesn't exist",
+        gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "an attachment is incomplete",
+        gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "no image is attached",
+        gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "a draw buffer has no attachment",
+        gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "the read buffer has no attachment",
+        gl::FRAMEBUFFER_UNSUPPORTED => "the combination of attachment formats is unsupported",
+        gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "attachments have mismatching sample counts",
+        gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS =>
+            "layered and non-layered attachments are mixed, or layered attachments have different targets",
+        _ => "unknown status",
+    }
+}
+
 fn create_fbo_internal(gl: &gl::Gl) -> gl::types::GLuint {
     let mut name = 0 as n::FrameBuffer;
     unsafe {
@@ -85,10 +101,25 @@ fn create_fbo_internal(gl: &gl::Gl) -> gl::types::GLuint {
     name
 }
 
+/// Kind of GL object wrapped by `Device::image_from_raw`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RawImageKind {
+    Texture,
+    Renderbuffer,
+}
+
+/// Vertex of a primitive providing the flat-shaded attributes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProvokingVertex {
+    First,
+    Last,
+}
+
 #[derive(Debug)]
 pub struct UnboundBuffer {
     name: n::RawBuffer,
     target: GLenum,
+    usage: buffer::Usage,
     requirements: memory::Requirements,
 }
 
@@ -96,6 +127,7 @@ pub struct UnboundBuffer {
 pub struct UnboundImage {
     image: n::ImageKind,
     channel: ChannelType,
+    kind: i::Kind,
     requirements: memory::Requirements,
 }
 
@@ -119,6 +151,77 @@ impl Device {
         }
     }
 
+    /// Select the provoking vertex convention used for flat shading.
+    ///
+    /// GL defaults to `Last`, while the other backends always use `First`.
+    /// Requires GL 3.2 or `ARB_provoking_vertex`; GL ES always uses `Last`.
+    pub fn set_provoking_vertex(&self, vertex: ProvokingVertex) {
+        if !self.share.private_caps.provoking_vertex {
+            error!("Selecting the provoking vertex is not supported");
+            return;
+        }
+
+        let convention = match vertex {
+            ProvokingVertex::First => gl::FIRST_VERTEX_CONVENTION,
+            ProvokingVertex::Last => gl::LAST_VERTEX_CONVENTION,
+        };
+        unsafe { self.share.context.ProvokingVertex(convention) };
+    }
+
+    /// Wrap an existing GL texture or renderbuffer created outside of gfx.
+    ///
+    /// Fails if `name` isn't an object of the given `kind`.
+    /// The GL object stays owned by the caller: don't pass the returned
+    /// image to `destroy_image`, simply drop it once it's no longer used.
+    pub fn image_from_raw(
+        &self, name: gl::types::GLuint, kind: RawImageKind, format: Format,
+    ) -> Result<n::Image, i::CreationError> {
+        let gl = &self.share.context;
+        let (valid, image_kind) = match kind {
+            RawImageKind::Texture => (unsafe { gl.IsTexture(name) }, n::ImageKind::Texture(name)),
+            RawImageKind::Renderbuffer => (unsafe { gl.IsRenderbuffer(name) }, n::ImageKind::Surface(name)),
+        };
+        if valid == gl::FALSE {
+            error!("{} is not a GL {:?}", name, kind);
+            return Err(i::CreationError::Kind);
+        }
+
+        Ok(n::Image {
+            kind: image_kind,
+            channel: format.base_format().1,
+            dimensions: None,
+        })
+    }
+
+    /// Wrap an existing GL buffer created outside of gfx.
+    ///
+    /// Fails if `name` isn't a buffer object.
+    /// The GL object stays owned by the caller: don't pass the returned
+    /// buffer to `destroy_buffer`, simply drop it once it's no longer used.
+    pub fn buffer_from_raw(
+        &self, name: gl::types::GLuint, usage: buffer::Usage,
+    ) -> Result<n::Buffer, buffer::CreationError> {
+        if unsafe { self.share.context.IsBuffer(name) } == gl::FALSE {
+            error!("{} is not a GL buffer", name);
+            return Err(buffer::CreationError::Other);
+        }
+
+        let target = if self.share.private_caps.buffer_role_change {
+            gl::ARRAY_BUFFER
+        } else {
+            match conv::buffer_usage_to_gl_target(usage) {
+                Some(target) => target,
+                None => return Err(buffer::CreationError::Usage(usage)),
+            }
+        };
+
+        Ok(n::Buffer {
+            raw: name,
+            target,
+            usage,
+        })
+    }
+
     pub fn create_shader_module_from_source(
         &self,
         data: &[u8],
@@ -288,7 +391,7 @@ impl d::Device<B> for Device {
         flags: CommandPoolCreateFlags,
     ) -> RawCommandPool {
         let fbo = create_fbo_internal(&self.share.context);
-        let limits = self.share.limits.into();
+        let limits = com::Limits::new(&self.share.limits, self.share.features, &self.share.private_caps);
         let memory = if flags.contains(CommandPoolCreateFlags::RESET_INDIVIDUAL) {
             BufferMemory::Individual {
                 storage: HashMap::new(),
@@ -373,6 +476,27 @@ impl d::Device<B> for Device {
             }
         };
 
+        let attributes = desc.attributes
+            .iter()
+            .map(|&a| {
+                let (size, format, vertex_attrib_fn) = conv::format_to_gl_format(a.element.format).unwrap();
+                n::AttributeDesc {
+                    location: a.location,
+                    offset: a.element.offset,
+                    binding: a.binding,
+                    size,
+                    format,
+                    vertex_attrib_fn,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if !share.private_caps.vertex_attrib_64bit &&
+            attributes.iter().any(|a| a.vertex_attrib_fn == n::VertexAttribFunction::Double) {
+            error!("64-bit vertex attributes are not supported");
+            return Err(pso::CreationError::Other);
+        }
+
         let program = {
             let name = unsafe { gl.CreateProgram() };
 
@@ -441,21 +565,10 @@ impl d::Device<B> for Device {
             primitive: conv::primitive_to_gl_primitive(desc.input_assembler.primitive),
             patch_size,
             blend_targets: desc.blender.targets.clone(),
+            alpha_coverage: desc.blender.alpha_coverage,
+            depth_stencil: desc.depth_stencil,
             vertex_buffers: desc.vertex_buffers.clone(),
-            attributes: desc.attributes
-                .iter()
-                .map(|&a| {
-                    let (size, format, vertex_attrib_fn) = conv::format_to_gl_format(a.element.format).unwrap();
-                    n::AttributeDesc {
-                        location: a.location,
-                        offset: a.element.offset,
-                        binding: a.binding,
-                        size,
-                        format,
-                        vertex_attrib_fn,
-                    }
-                })
-                .collect(),
+            attributes,
         })
     }
 
@@ -489,7 +602,7 @@ impl d::Device<B> for Device {
                     warn!("\tLog: {}", log);
                 }
             } else {
-                return Err(pso::CreationError::Other);
+                return Err(pso::CreationError::Shader(d::ShaderError::CompilationFailed(log)));
             }
 
             name
@@ -522,32 +635,53 @@ impl d::Device<B> for Device {
             gl.BindFramebuffer(target, name);
         }
 
-        let att_points = [
-            gl::COLOR_ATTACHMENT0,
-            gl::COLOR_ATTACHMENT1,
-            gl::COLOR_ATTACHMENT2,
-            gl::COLOR_ATTACHMENT3,
-        ];
-
+        // Color attachments are bound to `COLOR_ATTACHMENT0 + index`,
+        // matching the draw buffers set in `begin_subpass`.
+        let mut draw_buffers = Vec::new();
         let mut attachments_len = 0;
-        //TODO: exclude depth/stencil attachments from here
-        for (&att_point, view) in att_points.iter().zip(attachments.into_iter()) {
+        for (index, (attachment, view)) in pass.attachments.iter().zip(attachments.into_iter()).enumerate() {
             attachments_len += 1;
+            let att_point = match attachment.format {
+                Some(format) if format.is_depth() && format.is_stencil() => gl::DEPTH_STENCIL_ATTACHMENT,
+                Some(format) if format.is_depth() => gl::DEPTH_ATTACHMENT,
+                Some(format) if format.is_stencil() => gl::STENCIL_ATTACHMENT,
+                _ => {
+                    let att_point = gl::COLOR_ATTACHMENT0 + index as GLenum;
+                    draw_buffers.push(att_point);
+                    att_point
+                }
+            };
+            let view = view.borrow();
             if self.share.private_caps.framebuffer_texture {
-                Self::bind_target(gl, target, att_point, view.borrow());
+                Self::bind_target(gl, target, att_point, view);
+            } else if att_point == gl::DEPTH_STENCIL_ATTACHMENT {
+                // Older contexts may lack the combined attachment point.
+                Self::bind_target_compat(gl, target, gl::DEPTH_ATTACHMENT, view);
+                Self::bind_target_compat(gl, target, gl::STENCIL_ATTACHMENT, view);
             } else {
-                Self::bind_target_compat(gl, target, att_point, view.borrow());
+                Self::bind_target_compat(gl, target, att_point, view);
             }
         }
         assert_eq!(attachments_len, pass.attachments.len());
-        // attachments_len actually equals min(attachments.len(), att_points.len()) until the next assert
 
-        unsafe {
-            assert!(pass.attachments.len() <= att_points.len());
-            gl.DrawBuffers(attachments_len as _, att_points.as_ptr());
+        let status = unsafe {
+            gl.DrawBuffers(draw_buffers.len() as _, draw_buffers.as_ptr());
+            if draw_buffers.is_empty() {
+                // The read buffer defaults to `COLOR_ATTACHMENT0`, which makes
+                // depth-only framebuffers incomplete on GL before 4.1.
+                gl.BindFramebuffer(gl::READ_FRAMEBUFFER, name);
+                gl.ReadBuffer(gl::NONE);
+                gl.BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            }
             let status = gl.CheckFramebufferStatus(target);
-            assert_eq!(status, gl::FRAMEBUFFER_COMPLETE);
             gl.BindFramebuffer(target, 0);
+            status
+        };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            error!("Framebuffer {} is incomplete (0x{:x}): {}",
+                name, status, framebuffer_status_reason(status));
+            unsafe { gl.DeleteFramebuffers(1, &name) };
+            return Err(d::FramebufferError);
         }
         if let Err(err) = self.share.check() {
             //TODO: attachments have been consumed
@@ -652,6 +786,7 @@ impl d::Device<B> for Device {
         Ok(UnboundBuffer {
             name,
             target,
+            usage,
             requirements: memory::Requirements {
                 size,
                 alignment: 1, // TODO: do we need specific alignment for any use-case?
@@ -722,6 +857,7 @@ impl d::Device<B> for Device {
         Ok(n::Buffer {
             raw: unbound.name,
             target,
+            usage: unbound.usage,
         })
     }
 
@@ -814,15 +950,37 @@ impl d::Device<B> for Device {
         let int_format = match format {
             Format::Rgba8Unorm => gl::RGBA8,
             Format::Rgba8Srgb => gl::SRGB8_ALPHA8,
+            Format::D16Unorm => gl::DEPTH_COMPONENT16,
+            Format::X8D24Unorm => gl::DEPTH_COMPONENT24,
+            Format::D32Float => gl::DEPTH_COMPONENT32F,
+            Format::D24UnormS8Uint => gl::DEPTH24_STENCIL8,
+            Format::D32FloatS8Uint => gl::DEPTH32F_STENCIL8,
             _ => unimplemented!()
         };
 
         let channel = format.base_format().1;
 
+        let samples = match kind {
+            i::Kind::D2(_, _, _, samples) => samples,
+            _ => 1,
+        };
+        if samples as usize > self.share.private_caps.max_samples {
+            error!("Number of samples {} exceeds the device limit of {}",
+                samples, self.share.private_caps.max_samples);
+            return Err(i::CreationError::Samples(samples));
+        }
+
         let image = if num_levels > 1 ||
             usage.contains(i::Usage::STORAGE) ||
             usage.contains(i::Usage::SAMPLED)
         {
+            // Multisampled images are backed by renderbuffers,
+            // which can only be used as framebuffer attachments.
+            if samples > 1 {
+                error!("Multisampled images can't be sampled or used as storage");
+                return Err(i::CreationError::Samples(samples));
+            }
+
             let mut name = 0;
             unsafe { gl.GenTextures(1, &mut name) };
             match kind {
@@ -841,6 +999,10 @@ impl d::Device<B> for Device {
                     gl.BindRenderbuffer(gl::RENDERBUFFER, name);
                     gl.RenderbufferStorage(gl::RENDERBUFFER, int_format, w as _, h as _);
                 }
+                i::Kind::D2(w, h, 1, samples) => unsafe {
+                    gl.BindRenderbuffer(gl::RENDERBUFFER, name);
+                    gl.RenderbufferStorageMultisample(gl::RENDERBUFFER, samples as _, int_format, w as _, h as _);
+                }
                 _ => unimplemented!(),
             };
             n::ImageKind::Surface(name)
@@ -859,6 +1021,7 @@ impl d::Device<B> for Device {
         Ok(UnboundImage {
             image,
             channel,
+            kind,
             requirements: memory::Requirements {
                 size,
                 alignment: 1,
@@ -877,6 +1040,7 @@ impl d::Device<B> for Device {
         Ok(n::Image {
             kind: unbound.image,
             channel: unbound.channel,
+            dimensions: Some(unbound.kind),
         })
     }
 
@@ -177,6 +177,14 @@ pub struct PrivateCaps {
     pub map: bool,
     /// Indicates if we only have support via the EXT.
     pub sampler_anisotropy_ext: bool,
+    /// Maximum number of samples for multisampled renderbuffers.
+    pub max_samples: usize,
+    /// Can clear textures without a framebuffer
+    pub clear_texture: bool,
+    /// Can select the provoking vertex convention
+    pub provoking_vertex: bool,
+    /// Can use double-precision vertex attributes
+    pub vertex_attrib_64bit: bool,
 }
 
 /// OpenGL implementation information
@@ -354,6 +362,13 @@ pub fn query_all(gl: &gl::Gl) -> (Info, Features, LegacyFeatures, Limits, Privat
     ]) {
         features |= Features::INSTANCE_RATE;
     }
+    if info.is_supported(&[
+        Core(4, 0),
+        Es(3, 2),
+        Ext("GL_ARB_draw_buffers_blend"),
+    ]) {
+        features |= Features::INDEPENDENT_BLENDING;
+    }
 
     if info.is_supported(&[Core(4, 3), Es(3, 1)]) { // TODO: extension
         legacy |= LegacyFeatures::INDIRECT_EXECUTION;
@@ -453,6 +468,20 @@ pub fn query_all(gl: &gl::Gl) -> (Info, Features, LegacyFeatures, Limits, Privat
         sampler_anisotropy_ext:             !info.is_supported(&[Core(4,6),
                                                                 Ext ("GL_ARB_texture_filter_anisotropic")]) &&
                                             info.is_supported(&[Ext ("GL_EXT_texture_filter_anisotropic")]),
+        max_samples:                        if info.is_supported(&[Core(3,0),
+                                                                Es  (3,0),
+                                                                Ext ("GL_ARB_framebuffer_object")]) {
+                                                get_usize(gl, gl::MAX_SAMPLES)
+                                            } else {
+                                                1
+                                            },
+        clear_texture:                      info.is_supported(&[Core(4,4),
+                                                                Ext ("GL_ARB_clear_texture")]),
+        provoking_vertex:                   info.is_supported(&[Core(3,2),
+                                                                Ext ("GL_ARB_provoking_vertex"),
+                                                                Ext ("GL_EXT_provoking_vertex")]),
+        vertex_attrib_64bit:                info.is_supported(&[Core(4,1),
+                                                                Ext ("GL_ARB_vertex_attrib_64bit")]),
     };
 
     (info, features, legacy, limits, private)
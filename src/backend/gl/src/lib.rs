@@ -23,7 +23,7 @@ use std::thread::{self, ThreadId};
 use hal::{error, image};
 use hal::queue::{Queues, QueueFamilyId};
 
-pub use self::device::Device;
+pub use self::device::{Device, ProvokingVertex, RawImageKind};
 pub use self::info::{Info, PlatformName, Version};
 
 mod command;
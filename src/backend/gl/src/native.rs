@@ -1,6 +1,6 @@
 use std::cell::Cell;
 
-use hal::{format, image as i, pass, pso};
+use hal::{buffer, format, image as i, pass, pso};
 use hal::memory::Properties;
 
 use gl;
@@ -22,6 +22,7 @@ pub const DEFAULT_FRAMEBUFFER: FrameBuffer = 0;
 pub struct Buffer {
     pub(crate) raw: RawBuffer,
     pub(crate) target: gl::types::GLenum,
+    pub(crate) usage: buffer::Usage,
 }
 
 #[derive(Debug)]
@@ -44,6 +45,8 @@ pub struct GraphicsPipeline {
     pub(crate) primitive: gl::types::GLenum,
     pub(crate) patch_size: Option<gl::types::GLint>,
     pub(crate) blend_targets: Vec<pso::ColorBlendDesc>,
+    pub(crate) alpha_coverage: bool,
+    pub(crate) depth_stencil: Option<pso::DepthStencilDesc>,
     pub(crate) attributes: Vec<AttributeDesc>,
     pub(crate) vertex_buffers: Vec<pso::VertexBufferDesc>,
 }
@@ -58,6 +61,8 @@ pub struct Image {
     pub(crate) kind: ImageKind,
     // Required for clearing operations
     pub(crate) channel: format::ChannelType,
+    // Required for clearing sub-resources, unknown for images wrapped from raw GL objects
+    pub(crate) dimensions: Option<i::Kind>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
@@ -178,7 +183,7 @@ pub struct AttributeDesc {
     pub(crate) vertex_attrib_fn: VertexAttribFunction,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VertexAttribFunction {
     Float, // glVertexAttribPointer
     Integer, // glVertexAttribIPointer
@@ -4,6 +4,7 @@ use Starc;
 
 use hal;
 use hal::error;
+use hal::pso;
 
 use gl;
 use smallvec::SmallVec;
@@ -32,6 +33,9 @@ struct State {
     num_viewports: usize,
     // Currently set scissor rects.
     num_scissors: usize,
+    // Vertex attribute divisors, indexed by attribute location.
+    // None denotes that we don't know what is currently set.
+    divisors: Vec<Option<pso::InstanceRate>>,
 }
 
 impl State {
@@ -43,6 +47,7 @@ impl State {
             index_buffer: None,
             num_viewports: 0,
             num_scissors: 0,
+            divisors: Vec::new(),
         }
     }
 
@@ -51,6 +56,7 @@ impl State {
     fn flush(&mut self) {
         self.vao = false;
         self.index_buffer = None;
+        self.divisors.clear();
 
         // TOOD: reset viewports and scissors
         //       do we need to clear everything from 0..MAX_VIEWPORTS?
@@ -73,6 +79,15 @@ impl CommandQueue {
         }
     }
 
+    /// Get the vertex array object shared by all command buffers executed
+    /// on this queue. The name is `0` if VAOs aren't supported.
+    ///
+    /// External code can reuse it from within `with_gl`, which restores
+    /// the bindings on the next submission.
+    pub fn vertex_array(&self) -> ArrayBuffer {
+        self.vao
+    }
+
     /// Access the OpenGL directly via a closure. OpenGL types and enumerations
     /// can be found in the `gl` crate.
     ///
@@ -250,6 +265,34 @@ impl CommandQueue {
                     .collect();
             unsafe { gl.ScissorArrayv(0, scissors.len() as i32, scissors.as_ptr() as *const _)};
         }
+
+        // Reset vertex attribute divisors left over from instanced draws
+        for (location, divisor) in self.state.divisors.iter_mut().enumerate() {
+            if let Some(rate) = *divisor {
+                if rate != 0 {
+                    unsafe { gl.VertexAttribDivisor(location as _, 0) };
+                    *divisor = Some(0);
+                }
+            }
+        }
+    }
+
+    fn set_divisor(&mut self, location: gl::types::GLuint, rate: pso::InstanceRate) {
+        let location = location as usize;
+        if self.state.divisors.len() <= location {
+            self.state.divisors.resize(location + 1, None);
+        }
+        if self.state.divisors[location] == Some(rate) {
+            return;
+        }
+
+        if self.share.features.contains(hal::Features::INSTANCE_RATE) {
+            unsafe { self.share.context.VertexAttribDivisor(location as _, rate as _) };
+        } else if rate != 0 {
+            error!("Instanced arrays are not supported");
+            return;
+        }
+        self.state.divisors[location] = Some(rate);
     }
 
     fn process(&mut self, cmd: &com::Command, data_buf: &[u8]) {
@@ -426,6 +469,23 @@ impl CommandQueue {
             com::Command::SetBlendColor(color) => {
                 state::set_blend_color(&self.share.context, color);
             }
+            com::Command::ClearTexture(texture, level, offset, size, format, ty, ref value) => unsafe {
+                self.share.context.ClearTexSubImage(
+                    texture, level,
+                    offset[0], offset[1], offset[2],
+                    size[0], size[1], size[2],
+                    format, ty, value.as_ptr() as *const _,
+                );
+            }
+            com::Command::SetAlphaCoverage(enable) => {
+                state::set_alpha_coverage(&self.share.context, enable);
+            }
+            com::Command::SetDepth(depth) => {
+                state::bind_depth(&self.share.context, &depth);
+            }
+            com::Command::SetStencil(stencil, refs) => {
+                state::bind_stencil(&self.share.context, &stencil, refs, None);
+            }
             com::Command::ClearBufferColorF(draw_buffer, cv) => unsafe {
                 self.share.context.ClearBufferfv(gl::COLOR, draw_buffer, cv.as_ptr());
             }
@@ -443,7 +503,10 @@ impl CommandQueue {
                     _ => unreachable!(),
                 };
 
-                self.share.context.ClearBufferfi(target, 0, depth, stencil as _);
+                // Depth and stencil write masks set by pipelines also apply to clears.
+                let gl = &self.share.context;
+                state::unlock_depth_stencil_mask(gl);
+                gl.ClearBufferfi(target, 0, depth, stencil as _);
             }
             com::Command::DrawBuffers(draw_buffers) => unsafe {
                 let draw_buffers = Self::get::<gl::types::GLenum>(data_buf, draw_buffers);
@@ -473,25 +536,34 @@ impl CommandQueue {
                 self.share.context.UseProgram(program);
             }
             com::Command::BindBlendSlot(slot, ref blend) => {
-                state::bind_blend_slot(&self.share.context, slot, blend);
+                if self.share.features.contains(hal::Features::INDEPENDENT_BLENDING) {
+                    state::bind_blend_slot(&self.share.context, slot, blend);
+                } else {
+                    state::bind_blend(&self.share.context, blend);
+                }
             }
-            com::Command::BindAttribute(ref attribute, handle, stride, function_type) => unsafe {
+            com::Command::BindAttribute(ref attribute, handle, stride, function_type, rate) => {
                 use native::VertexAttribFunction::*;
 
                 let &native::AttributeDesc { location, size, format, offset, .. } = attribute;
                 let offset = offset as *const gl::types::GLvoid;
-                let gl = &self.share.context;
 
-                gl.BindBuffer(gl::ARRAY_BUFFER, handle);
+                unsafe {
+                    let gl = &self.share.context;
+
+                    gl.BindBuffer(gl::ARRAY_BUFFER, handle);
+
+                    match function_type {
+                        Float => gl.VertexAttribPointer(location, size, format, gl::FALSE, stride, offset),
+                        Integer => gl.VertexAttribIPointer(location, size, format, stride, offset),
+                        Double => gl.VertexAttribLPointer(location, size, format, stride, offset),
+                    }
 
-                match function_type {
-                    Float => gl.VertexAttribPointer(location, size, format, gl::FALSE, stride, offset),
-                    Integer => gl.VertexAttribIPointer(location, size, format, stride, offset),
-                    Double => gl.VertexAttribLPointer(location, size, format, stride, offset),
+                    gl.EnableVertexAttribArray(location);
+                    gl.BindBuffer(gl::ARRAY_BUFFER, 0);
                 }
 
-                gl.EnableVertexAttribArray(location);
-                gl.BindBuffer(gl::ARRAY_BUFFER, 0);
+                self.set_divisor(location, rate);
             }
             /*
             com::Command::UnbindAttribute(ref attribute) => unsafe {
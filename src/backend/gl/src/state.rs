@@ -225,8 +225,25 @@ pub fn unlock_color_mask(gl: &gl::Gl) {
     unsafe { gl.ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE) };
 }
 
+pub fn unlock_depth_stencil_mask(gl: &gl::Gl) {
+    unsafe {
+        gl.DepthMask(gl::TRUE);
+        gl.StencilMask(!0);
+    };
+}
+
 pub fn set_blend_color(gl: &gl::Gl, color: pso::ColorValue) {
     unsafe {
         gl.BlendColor(color[0], color[1], color[2], color[3])
     };
 }
+
+pub fn set_alpha_coverage(gl: &gl::Gl, enable: bool) {
+    unsafe {
+        if enable {
+            gl.Enable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+        } else {
+            gl.Disable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+        }
+    };
+}
@@ -119,6 +119,8 @@ pub mod buffer;
 pub mod image;
 /// Pipeline states
 pub mod pso;
+/// Shader source includes
+pub mod shader;
 /*
 /// Shaders
 pub mod shade;
@@ -0,0 +1,149 @@
+//! Shader source helpers.
+//!
+//! GLSL has no `#include` in base OpenGL, so sources registered in a
+//! `ShaderLibrary` can be pulled into other sources with a directive:
+//!
+//! ```glsl
+//! //! #include "lighting"
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+const DIRECTIVE_PREFIX: &str = "//!";
+const INCLUDE: &str = "#include";
+
+/// Error raised while preprocessing a shader source.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShaderError {
+    /// The included source hasn't been registered in the library.
+    MissingInclude(String),
+    /// The directive isn't followed by a quoted name.
+    InvalidDirective(String),
+    /// The included source (indirectly) includes itself.
+    RecursiveInclude(String),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShaderError::MissingInclude(ref name) |
+            ShaderError::RecursiveInclude(ref name) => write!(f, "{}: \"{}\"", self.description(), name),
+            ShaderError::InvalidDirective(ref line) => write!(f, "{}: {}", self.description(), line),
+        }
+    }
+}
+
+impl Error for ShaderError {
+    fn description(&self) -> &str {
+        match *self {
+            ShaderError::MissingInclude(_) => "Included source is not registered",
+            ShaderError::InvalidDirective(_) => "Malformed include directive",
+            ShaderError::RecursiveInclude(_) => "Recursive include",
+        }
+    }
+}
+
+/// A set of named shader sources which can be included by other sources.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderLibrary {
+    sources: HashMap<String, String>,
+}
+
+impl ShaderLibrary {
+    /// Create an empty library.
+    pub fn new() -> Self {
+        ShaderLibrary::default()
+    }
+
+    /// Register a source under `name`, replacing any previous source with the same name.
+    pub fn add_include<N, S>(&mut self, name: N, source: S)
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        self.sources.insert(name.into(), source.into());
+    }
+
+    /// Substitute every `//! #include "name"` line of `source` with the
+    /// registered source, recursively.
+    pub fn preprocess(&self, source: &str) -> Result<String, ShaderError> {
+        let mut output = String::with_capacity(source.len());
+        let mut stack = Vec::new();
+        self.expand(source, &mut stack, &mut output)?;
+        Ok(output)
+    }
+
+    fn expand<'a>(
+        &'a self,
+        source: &'a str,
+        stack: &mut Vec<&'a str>,
+        output: &mut String,
+    ) -> Result<(), ShaderError> {
+        for line in source.lines() {
+            match parse_include(line)? {
+                Some(name) => {
+                    if stack.contains(&name) {
+                        return Err(ShaderError::RecursiveInclude(name.to_string()));
+                    }
+                    let include = match self.sources.get(name) {
+                        Some(include) => include,
+                        None => return Err(ShaderError::MissingInclude(name.to_string())),
+                    };
+                    stack.push(name);
+                    self.expand(include, stack, output)?;
+                    stack.pop();
+                }
+                None => {
+                    output.push_str(line);
+                    output.push('\n');
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_include(line: &str) -> Result<Option<&str>, ShaderError> {
+    let trimmed = line.trim();
+    if !trimmed.starts_with(DIRECTIVE_PREFIX) {
+        return Ok(None);
+    }
+    let directive = trimmed[DIRECTIVE_PREFIX.len() ..].trim();
+    if !directive.starts_with(INCLUDE) {
+        return Ok(None);
+    }
+    let name = directive[INCLUDE.len() ..].trim();
+    if name.len() >= 2 && name.starts_with('"') && name.ends_with('"') {
+        Ok(Some(&name[1 .. name.len() - 1]))
+    } else {
+        Err(ShaderError::InvalidDirective(line.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_nested() {
+        let mut library = ShaderLibrary::new();
+        library.add_include("consts", "const float PI = 3.14159;");
+        library.add_include("math", "//! #include \"consts\"\nfloat half_pi() { return PI / 2.0; }");
+
+        let source = library.preprocess("#version 450\n//! #include \"math\"\nvoid main() {}").unwrap();
+        assert_eq!(source, "#version 450\nconst float PI = 3.14159;\nfloat half_pi() { return PI / 2.0; }\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn include_errors() {
+        let mut library = ShaderLibrary::new();
+        library.add_include("a", "//! #include \"b\"");
+        library.add_include("b", "//! #include \"a\"");
+
+        assert_eq!(library.preprocess("//! #include \"a\""), Err(ShaderError::RecursiveInclude("a".to_string())));
+        assert_eq!(library.preprocess("//! #include \"c\""), Err(ShaderError::MissingInclude("c".to_string())));
+        assert_eq!(library.preprocess("//! #include c"), Err(ShaderError::InvalidDirective("//! #include c".to_string())));
+    }
+}